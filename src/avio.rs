@@ -0,0 +1,133 @@
+//! Custom `AVIOContext` so [`crate::VideoPlayer::from_reader`] can demux from any `Read + Seek`.
+
+use std::{
+    io::{Read, Seek, SeekFrom},
+    os::raw::{c_int, c_void},
+    ptr,
+};
+
+use ffmpeg_the_third::{ffi, format::context::Input};
+
+const BUFFER_SIZE: usize = 4096;
+
+trait ReadSeek: Read + Seek {}
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// Must be dropped after the `Input` built on top of it closes the format context.
+pub struct AvioReader {
+    ctx: *mut ffi::AVIOContext,
+    opaque: *mut Box<dyn ReadSeek>,
+}
+
+impl AvioReader {
+    pub fn new(reader: impl Read + Seek + 'static) -> eyre::Result<Self> {
+        let boxed: Box<dyn ReadSeek> = Box::new(reader);
+        let opaque = Box::into_raw(Box::new(boxed));
+
+        let buffer = unsafe { ffi::av_malloc(BUFFER_SIZE) }.cast::<u8>();
+        if buffer.is_null() {
+            unsafe {
+                drop(Box::from_raw(opaque));
+            }
+            eyre::bail!("av_malloc failed to allocate the avio bounce buffer");
+        }
+
+        let ctx = unsafe {
+            ffi::avio_alloc_context(
+                buffer,
+                BUFFER_SIZE as c_int,
+                0,
+                opaque.cast::<c_void>(),
+                Some(read_packet),
+                None,
+                Some(seek),
+            )
+        };
+        if ctx.is_null() {
+            unsafe {
+                ffi::av_free(buffer.cast::<c_void>());
+                drop(Box::from_raw(opaque));
+            }
+            eyre::bail!("avio_alloc_context failed");
+        }
+
+        Ok(Self { ctx, opaque })
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut ffi::AVIOContext {
+        self.ctx
+    }
+}
+
+impl Drop for AvioReader {
+    fn drop(&mut self) {
+        unsafe {
+            let buffer = (*self.ctx).buffer;
+            ffi::avio_context_free(&mut self.ctx);
+            ffi::av_free(buffer.cast::<c_void>());
+            drop(Box::from_raw(self.opaque));
+        }
+    }
+}
+
+unsafe extern "C" fn read_packet(opaque: *mut c_void, buf: *mut u8, buf_size: c_int) -> c_int {
+    let reader = unsafe { &mut *opaque.cast::<Box<dyn ReadSeek>>() };
+    let slice = unsafe { std::slice::from_raw_parts_mut(buf, buf_size as usize) };
+    match reader.read(slice) {
+        Ok(0) => ffi::AVERROR_EOF,
+        Ok(n) => n as c_int,
+        Err(_) => ffi::AVERROR(ffi::EIO),
+    }
+}
+
+unsafe extern "C" fn seek(opaque: *mut c_void, offset: i64, whence: c_int) -> i64 {
+    let reader = unsafe { &mut *opaque.cast::<Box<dyn ReadSeek>>() };
+
+    if whence & ffi::AVSEEK_SIZE as c_int == ffi::AVSEEK_SIZE as c_int {
+        let Ok(current) = reader.stream_position() else {
+            return -1;
+        };
+        return reader
+            .seek(SeekFrom::End(0))
+            .and_then(|size| reader.seek(SeekFrom::Start(current)).map(|_| size))
+            .map_or(-1, |size| size as i64);
+    }
+
+    let pos = match whence {
+        ffi::SEEK_SET => SeekFrom::Start(offset as u64),
+        ffi::SEEK_CUR => SeekFrom::Current(offset),
+        ffi::SEEK_END => SeekFrom::End(offset),
+        _ => return -1,
+    };
+    reader.seek(pos).map_or(-1, |n| n as i64)
+}
+
+/// Opens an [`Input`] backed by `avio` instead of a real file.
+pub fn open_input(avio: &mut AvioReader) -> eyre::Result<Input> {
+    unsafe {
+        let ctx = ffi::avformat_alloc_context();
+        eyre::ensure!(!ctx.is_null(), "avformat_alloc_context failed");
+
+        (*ctx).pb = avio.as_mut_ptr();
+        (*ctx).flags |= ffi::AVFMT_FLAG_CUSTOM_IO as c_int;
+
+        let mut ctx = ctx;
+        let opened = ffi::avformat_open_input(
+            &mut ctx,
+            ptr::null(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+        );
+        if opened < 0 {
+            ffi::avformat_free_context(ctx);
+            eyre::bail!("avformat_open_input failed with error code {opened}");
+        }
+
+        if ffi::avformat_find_stream_info(ctx, ptr::null_mut()) < 0 {
+            ffi::avformat_close_input(&mut ctx);
+            eyre::bail!("avformat_find_stream_info failed");
+        }
+
+        Ok(Input::wrap(ctx))
+    }
+}
@@ -0,0 +1,44 @@
+//! Wall-clock based master clock. Used both to drive PTS-based A/V sync and to support
+//! pausing (freeze the position) and seeking (retarget it) from the keyboard.
+
+use std::time::{Duration, Instant};
+
+pub struct Clock {
+    anchor: Instant,
+    anchor_position: Duration,
+    paused: bool,
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Self {
+            anchor: Instant::now(),
+            anchor_position: Duration::ZERO,
+            paused: false,
+        }
+    }
+
+    pub fn position(&self) -> Duration {
+        if self.paused {
+            self.anchor_position
+        } else {
+            self.anchor_position + self.anchor.elapsed()
+        }
+    }
+
+    pub fn set_position(&mut self, position: Duration) {
+        self.anchor = Instant::now();
+        self.anchor_position = position;
+    }
+
+    pub fn pause(&mut self) {
+        if !self.paused {
+            self.anchor_position = self.position();
+            self.paused = true;
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+}
@@ -0,0 +1,104 @@
+//! Optional hardware-accelerated video decoding (VAAPI, NVDEC, ...), falling back to the
+//! software decode + `swscale` path when no hardware device is available.
+
+use std::{os::raw::c_void, ptr};
+
+use ffmpeg_the_third::{codec, ffi, frame::Video};
+
+pub struct HwAccel {
+    device_ctx: *mut ffi::AVBufferRef,
+    /// Stashed on the heap so `get_format` can read it back out of `AVCodecContext::opaque`.
+    wanted_pix_fmt: *mut ffi::AVPixelFormat,
+}
+
+impl HwAccel {
+    /// Returns `None` if no usable hardware device was found, never an error.
+    pub fn probe(codec: &codec::Codec) -> Option<Self> {
+        let mut index = 0;
+        loop {
+            let config = unsafe { ffi::avcodec_get_hw_config(codec.as_ptr(), index) };
+            if config.is_null() {
+                return None;
+            }
+            index += 1;
+
+            let config = unsafe { &*config };
+            if config.methods & ffi::AV_CODEC_HW_CONFIG_METHOD_HW_DEVICE_CTX as i32 == 0 {
+                continue;
+            }
+
+            let mut device_ctx = ptr::null_mut();
+            let created = unsafe {
+                ffi::av_hwdevice_ctx_create(
+                    &mut device_ctx,
+                    config.device_type,
+                    ptr::null(),
+                    ptr::null_mut(),
+                    0,
+                )
+            };
+            if created < 0 {
+                continue;
+            }
+
+            let wanted_pix_fmt = Box::into_raw(Box::new(config.pix_fmt));
+            return Some(Self {
+                device_ctx,
+                wanted_pix_fmt,
+            });
+        }
+    }
+
+    /// # Safety
+    /// `ctx` must point at a live, not-yet-opened `AVCodecContext`.
+    pub unsafe fn attach(&self, ctx: *mut ffi::AVCodecContext) {
+        unsafe {
+            (*ctx).hw_device_ctx = ffi::av_buffer_ref(self.device_ctx);
+            (*ctx).opaque = self.wanted_pix_fmt.cast::<c_void>();
+            (*ctx).get_format = Some(get_format);
+        }
+    }
+
+    pub fn is_hw_frame(frame: &Video) -> bool {
+        unsafe { !(*frame.as_ptr()).hw_frames_ctx.is_null() }
+    }
+
+    pub fn transfer_to_software(frame: &Video) -> eyre::Result<Video> {
+        let mut software = Video::empty();
+        let transferred =
+            unsafe { ffi::av_hwframe_transfer_data(software.as_mut_ptr(), frame.as_ptr(), 0) };
+        eyre::ensure!(
+            transferred >= 0,
+            "av_hwframe_transfer_data failed with error code {transferred}"
+        );
+        Ok(software)
+    }
+}
+
+impl Drop for HwAccel {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::av_buffer_unref(&mut self.device_ctx);
+            drop(Box::from_raw(self.wanted_pix_fmt));
+        }
+    }
+}
+
+unsafe extern "C" fn get_format(
+    ctx: *mut ffi::AVCodecContext,
+    fmts: *const ffi::AVPixelFormat,
+) -> ffi::AVPixelFormat {
+    unsafe {
+        let wanted = *(*ctx).opaque.cast::<ffi::AVPixelFormat>();
+        let mut candidate = fmts;
+        while *candidate != ffi::AVPixelFormat::AV_PIX_FMT_NONE {
+            if *candidate == wanted {
+                return *candidate;
+            }
+            candidate = candidate.add(1);
+        }
+        // Negotiation failed to find our hw format in the candidate list; fall back to
+        // whatever the decoder offered first rather than aborting decode entirely.
+        *fmts
+    }
+}
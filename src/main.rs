@@ -1,26 +1,58 @@
 #![feature(iter_map_windows)]
 
+mod avio;
+mod clock;
+mod hwaccel;
+mod osd;
+mod subtitle;
+
 use std::{
+    collections::VecDeque,
+    io::{Read, Seek},
     iter,
-    thread::sleep,
-    time::{Duration, Instant},
+    path::Path,
+    time::Duration,
 };
 
+use avio::AvioReader;
+use clock::Clock;
 use eyre::ContextCompat;
 use ffmpeg_the_third::{
-    self as ffmpeg, Packet, Stream, codec,
+    self as ffmpeg, Packet, codec, decoder, ffi,
     filter::Graph,
     format::{self, Pixel, context::Input},
     frame::{Audio, Video},
     media,
-    software::scaling::Flags,
+    software::scaling::{self, Flags},
     threading,
 };
+use hwaccel::HwAccel;
+use osd::Osd;
+use subtitle::{SubtitleContent, SubtitleCue};
 use macroquad::{
-    audio::{Sound, load_sound_from_bytes, play_sound_once},
+    audio::{Sound, load_sound_from_bytes, play_sound_once, stop_sound},
     prelude::*,
 };
 
+/// How far left/right arrow seeking jumps.
+const SEEK_STEP: Duration = Duration::from_secs(10);
+
+/// Frames kept ready before playback starts.
+const PREFETCH_FRAMES: usize = 16;
+/// Hard cap on the video frame ring buffer; demuxing stalls once it is reached.
+const MAX_QUEUED_FRAMES: usize = 32;
+
+/// Whether to try the hardware decode path (VAAPI/NVDEC/...) before falling back to software.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum DecodeMode {
+    /// Probe for a working hardware device and use it if one is found; fall back to software
+    /// transparently if none is available or negotiation fails.
+    #[default]
+    PreferHardware,
+    /// Always decode on the CPU, same as before hardware support existed.
+    SoftwareOnly,
+}
+
 fn retain_aspect_ratio_scale(frame: &Video) -> Result<Texture2D, eyre::Error> {
     let src_width = frame.width();
     let src_height = frame.height();
@@ -63,149 +95,262 @@ fn retain_aspect_ratio_scale(frame: &Video) -> Result<Texture2D, eyre::Error> {
     eyre::Result::Ok(texture)
 }
 
-fn decode_frame<'a, T: Iterator<Item = (Stream<'a>, Packet)>>(
-    video_packets: Vec<(Stream<'a>, Packet)>,
-    audio_packets: T,
-) -> eyre::Result<(
-    impl Iterator<Item = Texture2D> + use<'a, T>,
-    impl Iterator<Item = Audio> + use<'a, T>,
-    f64,
-)> {
-    let (avg_frame_rate, vstream) = video_packets
-        .first()
-        .map(|x| (x.0.avg_frame_rate().into(), x.0.parameters()))
-        .context("not possible")?;
-
-    let mut audio_packets = audio_packets.peekable();
-    let astream = audio_packets
-        .peek()
-        .map(|x| x.0.parameters())
-        .context("not possible")?;
-
-    let mut vcodec = codec::context::Context::from_parameters(vstream)?;
-    let acodec = codec::context::Context::from_parameters(astream)?;
-    if let Ok(paralleism) = std::thread::available_parallelism() {
-        vcodec.set_threading(threading::Config {
-            kind: threading::Type::Frame,
-            count: paralleism.get(),
-        });
-    }
-
-    let mut vdecoder = vcodec.decoder().video()?;
-    let mut adecoder = acodec.decoder().audio()?;
-
-    let mut scaler = ffmpeg::software::scaling::Context::get(
-        vdecoder.format(),
-        vdecoder.width(),
-        vdecoder.height(),
-        Pixel::RGBA,
-        vdecoder.width(),
-        vdecoder.height(),
-        Flags::BILINEAR,
-    )?;
-
-    let audio = audio_packets
-        .map(|x| x.1)
-        .chain(std::iter::once(Packet::empty()))
-        .filter_map(move |packet| {
-            unsafe {
-                if packet.is_empty() {
-                    adecoder.send_eof().ok()?;
-                } else {
-                    adecoder.send_packet(&packet).ok()?;
-                }
-            }
-            let mut decoded_audio = Audio::empty();
-            let mut audio = Vec::new();
-            while adecoder.receive_frame(&mut decoded_audio).is_ok() {
-                let mut resampler = decoded_audio
-                    .resampler2(
-                        format::Sample::I16(format::sample::Type::Packed),
-                        decoded_audio.ch_layout(),
-                        decoded_audio.rate(),
-                    )
-                    .ok()?;
-                let mut wav = Audio::empty();
-                resampler.run(&decoded_audio, &mut wav).ok()?;
-                audio.push(wav);
-            }
-            Some(audio)
-        })
-        .flatten();
-
-    let video = video_packets
-        .into_iter()
-        .map(|x| x.1)
-        .chain(std::iter::once(Packet::empty()))
-        .filter_map(move |packet| {
-            unsafe {
-                if packet.is_empty() {
-                    vdecoder.send_eof().ok()?;
-                } else {
-                    vdecoder.send_packet(&packet).ok()?;
-                }
-            }
-            let mut decoded_video = Video::empty();
-            let mut video = Vec::new();
-            while vdecoder.receive_frame(&mut decoded_video).is_ok() {
-                let mut rgb_frame = Video::empty();
-                scaler.run(&decoded_video, &mut rgb_frame).ok()?;
-                video.push(rgb_frame);
-            }
-            Some(video)
-        })
-        .flatten()
-        .map(|frame| retain_aspect_ratio_scale(&frame))
-        .map_while(Result::<_, eyre::Error>::ok);
+/// Where the video demux/decode loop currently is, modeled on the nihav player's state machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DemuxState {
+    /// Filling the ring buffer up to `PREFETCH_FRAMES` before playback starts.
+    Prefetch,
+    /// Steady state: pull and decode the next packet.
+    Normal,
+    /// The ring buffer is full; demuxing is paused until the consumer drains it.
+    Waiting,
+    /// EOF was hit; draining whatever frames remain buffered inside the decoder.
+    Flush,
+    /// EOF reached and the decoder fully drained.
+    End,
+    /// A demux or decode call failed; the player gives up advancing.
+    Error,
+}
 
-    Ok((video, audio, avg_frame_rate))
+/// A decoded frame paired with its presentation timestamp, relative to playback start.
+struct QueuedFrame {
+    pts: Duration,
+    texture: Texture2D,
 }
 
-struct VideoPlayer<Iter: iter::Iterator<Item = Texture2D>> {
-    frames: iter::Peekable<Iter>,
+struct VideoPlayer {
+    input: Input,
+    /// Keeps a custom AVIO source alive for as long as `input` does; declared after it so it is
+    /// dropped after `input` closes the format context that points into it.
+    _avio: Option<AvioReader>,
+    vstream_id: usize,
+    vdecoder: decoder::Video,
+    /// Built lazily from the first decoded frame's actual format: on the hardware path that is
+    /// only known once a frame has been transferred back to system memory, not at decoder
+    /// construction time.
+    scaler: Option<scaling::Context>,
+    scaler_format: Option<(Pixel, u32, u32)>,
+    /// `Some` when a hardware device context was successfully attached to the decoder.
+    hwaccel: Option<HwAccel>,
+    /// Seconds represented by one tick of the video stream's `time_base`.
+    time_base_secs: f64,
+    state: DemuxState,
+    frames: VecDeque<QueuedFrame>,
     frames_played: usize,
     frame_rate: f64,
     audio: Sound,
-    instant: Instant,
-    broken: Duration,
+    /// Full decoded audio track, kept around so a seek can rebuild a `Sound` starting at the
+    /// target offset instead of only ever being able to replay it from the beginning.
+    audio_track: AudioTrack,
+    /// Master clock: wall-clock time since playback started, standing in for the audio
+    /// playback position (macroquad exposes no way to query it) and set once the first
+    /// frame is drawn and `play_sound_once` fires. Also doubles as the pause/seek clock.
+    clock: Option<Clock>,
+    /// Last texture actually presented, redrawn while holding for an early frame.
+    last_texture: Option<Texture2D>,
+    /// Whole subtitle track, decoded up front (like `audio_track`) and displayed by scanning for
+    /// whichever cue's `[start, end)` window contains the current clock position.
+    subtitles: Vec<SubtitleCue>,
+    total_duration: Duration,
+    osd: Osd,
+}
+
+/// Resamples every frame currently buffered inside `decoder` to packed i16 and appends it to
+/// `frames`. Shared between the per-packet decode loop and the final EOF flush in
+/// `build_video_player`.
+fn drain_audio_frames(decoder: &mut decoder::Audio, frames: &mut Vec<Audio>) -> eyre::Result<()> {
+    let mut decoded = Audio::empty();
+    while decoder.receive_frame(&mut decoded).is_ok() {
+        let mut resampler = decoded.resampler2(
+            format::Sample::I16(format::sample::Type::Packed),
+            decoded.ch_layout(),
+            decoded.rate(),
+        )?;
+        let mut resampled = Audio::empty();
+        resampler.run(&decoded, &mut resampled)?;
+        frames.push(resampled);
+    }
+    Ok(())
 }
 
-impl<Iter: Iterator<Item = Texture2D>> VideoPlayer<Iter> {
-    fn frame_limiter(&mut self) {
-        let frame_duration = Duration::from_secs_f64(1. / self.frame_rate);
-        let elapsed = self.instant.elapsed();
+impl VideoPlayer {
+    /// Pulls the next packet belonging to the video stream, discarding everything else.
+    fn next_video_packet(&mut self) -> Option<Packet> {
+        loop {
+            let (stream, packet) = self.input.packets().filter_map(Result::ok).next()?;
+            if stream.index() == self.vstream_id {
+                return Some(packet);
+            }
+        }
+    }
 
-        if elapsed < frame_duration {
-            if frame_duration - elapsed >= self.broken {
-                sleep(frame_duration - elapsed - self.broken);
-                self.broken = Duration::ZERO;
+    /// Drains every frame currently buffered inside the decoder into the ring buffer.
+    fn drain_decoder(&mut self) -> eyre::Result<()> {
+        let mut decoded = Video::empty();
+        while self.vdecoder.receive_frame(&mut decoded).is_ok() {
+            let pts = decoded
+                .pts()
+                .map(|pts| Duration::from_secs_f64((pts as f64 * self.time_base_secs).max(0.)))
+                .unwrap_or_default();
+
+            let software_frame = if self.hwaccel.is_some() && HwAccel::is_hw_frame(&decoded) {
+                HwAccel::transfer_to_software(&decoded)?
             } else {
-                sleep(Duration::ZERO);
-                self.broken = self.broken.saturating_sub(frame_duration - elapsed);
+                decoded.clone()
+            };
+
+            let mut rgba_frame = Video::empty();
+            self.scaler_for(&software_frame)?
+                .run(&software_frame, &mut rgba_frame)?;
+            let texture = retain_aspect_ratio_scale(&rgba_frame)?;
+            self.frames.push_back(QueuedFrame { pts, texture });
+        }
+        Ok(())
+    }
+
+    /// Returns the RGBA `swscale` context for `frame`'s format/size, (re)building it if the
+    /// format or dimensions changed since the last call — which happens at least once on the
+    /// hardware path, since the real pixel format is only known after the first transfer.
+    fn scaler_for(&mut self, frame: &Video) -> eyre::Result<&mut scaling::Context> {
+        let key = (frame.format(), frame.width(), frame.height());
+        if self.scaler_format != Some(key) {
+            self.scaler = Some(scaling::Context::get(
+                frame.format(),
+                frame.width(),
+                frame.height(),
+                Pixel::RGBA,
+                frame.width(),
+                frame.height(),
+                Flags::BILINEAR,
+            )?);
+            self.scaler_format = Some(key);
+        }
+        Ok(self.scaler.as_mut().expect("just built above"))
+    }
+
+    fn decode_and_push(&mut self, packet: &Packet) -> eyre::Result<()> {
+        unsafe {
+            self.vdecoder.send_packet(packet)?;
+        }
+        self.drain_decoder()
+    }
+
+    fn flush_decoder(&mut self) -> eyre::Result<()> {
+        unsafe {
+            self.vdecoder.send_eof()?;
+        }
+        self.drain_decoder()
+    }
+
+    /// Advances the demux state machine, topping the ring buffer back up whenever there is room.
+    fn fill_queue(&mut self) {
+        loop {
+            match self.state {
+                DemuxState::End | DemuxState::Error => return,
+                DemuxState::Waiting => {
+                    if self.frames.len() >= MAX_QUEUED_FRAMES {
+                        return;
+                    }
+                    self.state = DemuxState::Normal;
+                }
+                DemuxState::Flush => {
+                    self.state = match self.flush_decoder() {
+                        Ok(()) => DemuxState::End,
+                        Err(_) => DemuxState::Error,
+                    };
+                    return;
+                }
+                DemuxState::Prefetch | DemuxState::Normal => {
+                    if self.frames.len() >= MAX_QUEUED_FRAMES {
+                        self.state = DemuxState::Waiting;
+                        return;
+                    }
+
+                    match self.next_video_packet() {
+                        Some(packet) => {
+                            if self.decode_and_push(&packet).is_err() {
+                                self.state = DemuxState::Error;
+                                return;
+                            }
+                        }
+                        None => self.state = DemuxState::Flush,
+                    }
+
+                    if self.state == DemuxState::Prefetch && self.frames.len() >= PREFETCH_FRAMES {
+                        self.state = DemuxState::Normal;
+                        return;
+                    }
+                }
             }
-        } else {
-            if self.broken > Duration::from_millis(1000) {
-                error!(
-                    "compensation frames exceed 1000ms in total, please make sure settings are correct!"
-                );
+        }
+    }
+
+    /// The master clock driving presentation: elapsed time since playback started, frozen
+    /// while paused.
+    fn clock(&self) -> Duration {
+        self.clock.as_ref().map_or(Duration::ZERO, Clock::position)
+    }
+
+    /// Advances `self.last_texture` to whichever queued frame should be on screen right now,
+    /// dropping frames that fell too far behind and holding on the current one when the next
+    /// is still early. Mirrors the nihav player's PTS-driven presentation instead of a fixed
+    /// `1/frame_rate` limiter.
+    fn sync_to_clock(&mut self) {
+        let clock = self.clock();
+        let frame_interval = Duration::from_secs_f64(1. / self.frame_rate);
+
+        while let Some(front) = self.frames.front() {
+            if clock.saturating_sub(front.pts) > frame_interval {
+                self.frames.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        let Some(front) = self.frames.front() else {
+            return;
+        };
+        if front.pts <= clock {
+            self.last_texture = Some(self.frames.pop_front().unwrap().texture);
+        }
+    }
+
+    /// Every cue whose `[start, end)` window contains the current clock position - a subtitle
+    /// can carry several simultaneous rects (e.g. two speakers' lines at once).
+    fn active_subtitles(&self) -> impl Iterator<Item = &SubtitleCue> {
+        let clock = self.clock();
+        self.subtitles
+            .iter()
+            .filter(move |cue| cue.start <= clock && clock < cue.end)
+    }
+
+    fn draw_subtitle(&self) {
+        let mut text_y = screen_height() - 90.;
+        for cue in self.active_subtitles() {
+            match &cue.content {
+                SubtitleContent::Text(text) => {
+                    draw_text(text, 20., text_y, 32., WHITE);
+                    text_y -= 36.;
+                }
+                SubtitleContent::Bitmap { texture, x, y } => {
+                    draw_texture(texture, *x as f32, *y as f32, WHITE);
+                }
             }
-            self.broken += elapsed - frame_duration;
-            warn!(
-                "took tooooo long to render!\nwill try to compensate it by early playing the few next frames by {:?}",
-                self.broken
-            );
         }
-        self.instant = Instant::now();
     }
 
     fn draw_video_by_frame(&mut self) {
         clear_background(BLACK);
 
         if self.frames_played == 0 {
+            self.clock = Some(Clock::new());
             play_sound_once(&self.audio);
         }
 
-        let Some(texture) = &self.frames.next() else {
+        self.fill_queue();
+        self.sync_to_clock();
+
+        let Some(texture) = &self.last_texture else {
             return;
         };
 
@@ -220,91 +365,329 @@ impl<Iter: Iterator<Item = Texture2D>> VideoPlayer<Iter> {
             text_color,
         );
 
-        self.frame_limiter();
+        self.draw_subtitle();
+        self.osd
+            .draw(self.clock(), self.total_duration, self.is_paused());
 
         self.frames_played += 1;
     }
+
+    /// Toggles pause: while paused the clock is frozen (so `sync_to_clock` just keeps holding
+    /// the current texture) but demuxing/decoding keeps topping up the ring buffer. Macroquad
+    /// has no way to pause a `Sound` mid-playback, so pausing stops it outright; resuming
+    /// restarts it via a seek back to the frozen video position.
+    async fn toggle_pause(&mut self) -> eyre::Result<()> {
+        let Some(clock) = &mut self.clock else {
+            return Ok(());
+        };
+        if clock.is_paused() {
+            let position = clock.position();
+            self.seek_to(position).await
+        } else {
+            clock.pause();
+            stop_sound(&self.audio);
+            Ok(())
+        }
+    }
+
+    fn is_paused(&self) -> bool {
+        self.clock.as_ref().is_some_and(Clock::is_paused)
+    }
+
+    /// Seeks to an absolute position: flushes the video decoder, clears the ring buffer, seeks
+    /// the demuxer to the nearest keyframe at or before `position`, and rebuilds the audio
+    /// `Sound` from the corresponding offset in the full decoded track.
+    async fn seek_to(&mut self, position: Duration) -> eyre::Result<()> {
+        let position_us = position.as_micros().min(i64::MAX as u128) as i64;
+        self.input.seek(position_us, ..position_us)?;
+
+        self.vdecoder.flush();
+        self.frames.clear();
+        self.last_texture = None;
+        self.state = DemuxState::Prefetch;
+        self.fill_queue();
+
+        stop_sound(&self.audio);
+        let wav = self.audio_track.encode_wav_from(position)?;
+        self.audio = load_sound_from_bytes(&wav).await?;
+        play_sound_once(&self.audio);
+
+        let mut clock = Clock::new();
+        clock.set_position(position);
+        self.clock = Some(clock);
+
+        Ok(())
+    }
+
+    async fn seek_relative(&mut self, delta: Duration, forward: bool) -> eyre::Result<()> {
+        let current = self.clock();
+        let target = if forward {
+            current + delta
+        } else {
+            current.saturating_sub(delta)
+        };
+        self.seek_to(target).await?;
+        self.osd.note_seek();
+        Ok(())
+    }
+
+    /// Single-steps one frame forward while paused, without touching the audio.
+    fn step_forward(&mut self) {
+        self.fill_queue();
+        let Some(frame) = self.frames.pop_front() else {
+            return;
+        };
+        if let Some(clock) = &mut self.clock {
+            clock.set_position(frame.pts);
+        }
+        self.last_texture = Some(frame.texture);
+    }
+
+    /// Single-steps one frame backward while paused, by seeking just shy of the current frame.
+    async fn step_backward(&mut self) -> eyre::Result<()> {
+        let frame_interval = Duration::from_secs_f64(1. / self.frame_rate);
+        let target = self.clock().saturating_sub(frame_interval);
+        self.seek_to(target).await?;
+        if let Some(clock) = &mut self.clock {
+            clock.pause();
+        }
+        stop_sound(&self.audio);
+        Ok(())
+    }
+}
+
+impl VideoPlayer {
+    /// Opens a video file from the filesystem, same as before.
+    pub async fn from_path(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        Self::from_path_with_mode(path, DecodeMode::default()).await
+    }
+
+    pub async fn from_path_with_mode(
+        path: impl AsRef<Path>,
+        mode: DecodeMode,
+    ) -> eyre::Result<Self> {
+        let input = ffmpeg::format::input(path)?;
+        build_video_player(input, None, mode).await
+    }
+
+    /// Opens a video from any `Read + Seek` source by wiring a custom AVIO context, for
+    /// contexts (WASM/macroquad, network downloads, embedded assets) where there is no real
+    /// filesystem to hand FFmpeg a path for.
+    pub async fn from_reader(reader: impl Read + Seek + 'static) -> eyre::Result<Self> {
+        Self::from_reader_with_mode(reader, DecodeMode::default()).await
+    }
+
+    pub async fn from_reader_with_mode(
+        reader: impl Read + Seek + 'static,
+        mode: DecodeMode,
+    ) -> eyre::Result<Self> {
+        let mut avio_reader = AvioReader::new(reader)?;
+        let input = avio::open_input(&mut avio_reader)?;
+        build_video_player(input, Some(avio_reader), mode).await
+    }
 }
 
-async fn get_video_player(
-    input: &mut Input,
-) -> eyre::Result<VideoPlayer<impl Iterator<Item = Texture2D>>> {
-    let vstream_id = input
+/// Builds a [`VideoPlayer`] by demuxing `input`.
+///
+/// Scope note: only the video path is streaming/flat-memory (see [`DemuxState`]). Audio and
+/// subtitles are still decoded fully into memory up front and are linear in file length - on a
+/// multi-hour file, startup time and memory for those two tracks scale with the file, not just
+/// the video ring buffer. This is a real, accepted limitation, not an oversight: macroquad's
+/// `Sound` has no API to feed it incrementally, so the whole track has to exist before playback
+/// can start.
+async fn build_video_player(
+    mut input: Input,
+    avio: Option<AvioReader>,
+    mode: DecodeMode,
+) -> eyre::Result<VideoPlayer> {
+    let vstream = input
         .streams()
         .best(media::Type::Video)
-        .context("stream not found")?
-        .index();
+        .context("stream not found")?;
+    let vstream_id = vstream.index();
+    let avg_frame_rate: f64 = vstream.avg_frame_rate().into();
+    let time_base_secs: f64 = vstream.time_base().into();
+    let vparams = vstream.parameters();
 
-    let astream_id = input
+    let astream = input
         .streams()
         .best(media::Type::Audio)
-        .context("stream not found")?
-        .index();
-
-    let packets = input.packets().filter_map(Result::ok);
+        .context("stream not found")?;
+    let astream_id = astream.index();
+    let aparams = astream.parameters();
+
+    // The subtitle track is optional; most files in the wild don't have one.
+    let sstream = input.streams().best(media::Type::Subtitle);
+    let sstream_id = sstream.as_ref().map(format::stream::Stream::index);
+    let stime_base_secs: f64 = sstream
+        .as_ref()
+        .map(|stream| stream.time_base().into())
+        .unwrap_or(1.);
+    let mut sdecoder = sstream.and_then(|stream| {
+        let scodec = codec::context::Context::from_parameters(stream.parameters()).ok()?;
+        scodec.decoder().subtitle().ok()
+    });
+
+    // Eager audio+subtitle decode pass; see the scope note on this function.
+    let acodec = codec::context::Context::from_parameters(aparams)?;
+    let mut adecoder = acodec.decoder().audio()?;
 
-    let (video_packets, not_video_packets): (Vec<_>, Vec<_>) =
-        packets.partition(|x| x.0.index() == vstream_id);
+    let mut audio_frames = Vec::new();
+    let mut subtitles = Vec::new();
+    for (stream, packet) in input.packets().filter_map(Result::ok) {
+        if stream.index() == astream_id {
+            unsafe {
+                adecoder.send_packet(&packet)?;
+            }
+            drain_audio_frames(&mut adecoder, &mut audio_frames)?;
+        } else if let Some(sdecoder) = sstream_id
+            .filter(|&id| id == stream.index())
+            .and(sdecoder.as_mut())
+        {
+            let packet_pts = packet
+                .pts()
+                .map(|pts| Duration::from_secs_f64((pts as f64 * stime_base_secs).max(0.)))
+                .unwrap_or_default();
+            let mut decoded_subtitle = ffmpeg::codec::subtitle::Subtitle::default();
+            if sdecoder.decode(&packet, &mut decoded_subtitle).unwrap_or(false) {
+                subtitles.extend(subtitle::cues_from(packet_pts, &decoded_subtitle));
+                // `Subtitle` has no `Drop` that calls this for us; rects carry owned
+                // palette/bitmap buffers that would otherwise leak on every cue.
+                unsafe {
+                    ffi::avsubtitle_free(decoded_subtitle.as_mut_ptr());
+                }
+            }
+        }
+    }
+    unsafe {
+        adecoder.send_eof()?;
+    }
+    drain_audio_frames(&mut adecoder, &mut audio_frames)?;
 
-    let audio_packets = not_video_packets
-        .into_iter()
-        .filter(move |x| x.0.index() == astream_id);
+    let total_duration = Duration::from_secs_f64((input.duration().max(0) as f64) / 1_000_000.);
 
-    let (frames, audio, frame_rate) = decode_frame(video_packets, audio_packets)?;
+    let audio_track = AudioTrack::collect(audio_frames.into_iter().peekable())?;
+    let buffer = audio_track.encode_wav()?;
+    let sound = load_sound_from_bytes(&buffer).await?;
 
-    let audio = audio.peekable();
+    // Rewind so the player's own demux loop can pull the video stream from the top.
+    input.seek(0, ..)?;
 
-    let buffer = build_wav_from_raw(audio)?;
+    let mut vcodec = codec::context::Context::from_parameters(vparams.clone())?;
+    if let Ok(parallelism) = std::thread::available_parallelism() {
+        vcodec.set_threading(threading::Config {
+            kind: threading::Type::Frame,
+            count: parallelism.get(),
+        });
+    }
 
-    let sound = load_sound_from_bytes(&buffer).await?;
+    // Hardware device context has to be attached before the decoder is opened, since
+    // `avcodec_open2` is where FFmpeg first negotiates the pixel format via `get_format`.
+    let hwaccel = match mode {
+        DecodeMode::SoftwareOnly => None,
+        DecodeMode::PreferHardware => decoder::find(vparams.id())
+            .as_ref()
+            .and_then(HwAccel::probe),
+    };
+    if let Some(hwaccel) = &hwaccel {
+        unsafe {
+            hwaccel.attach(vcodec.as_mut_ptr());
+        }
+    }
 
-    let video_player = VideoPlayer {
-        frames: frames.peekable(),
+    let vdecoder = vcodec.decoder().video()?;
+
+    let mut video_player = VideoPlayer {
+        input,
+        _avio: avio,
+        vstream_id,
+        vdecoder,
+        scaler: None,
+        scaler_format: None,
+        hwaccel,
+        time_base_secs,
+        state: DemuxState::Prefetch,
+        frames: VecDeque::with_capacity(MAX_QUEUED_FRAMES),
         frames_played: 0,
-        frame_rate,
+        frame_rate: avg_frame_rate,
         audio: sound,
-        instant: Instant::now(),
-        broken: Duration::ZERO,
+        audio_track,
+        clock: None,
+        last_texture: None,
+        subtitles,
+        total_duration,
+        osd: Osd::new(),
     };
+    video_player.fill_queue();
 
     Ok(video_player)
 }
 
-fn build_wav_from_raw(
-    mut audio: iter::Peekable<impl Iterator<Item = Audio>>,
-) -> Result<Vec<u8>, eyre::Error> {
-    let mut buffer = Vec::new();
-    let cursor = std::io::Cursor::new(&mut buffer);
-
-    let first = audio.peek().context("empty audio stream")?;
-
-    let channels = first.ch_layout().channels();
-
-    let mut writer = hound::WavWriter::new(
-        cursor,
-        hound::WavSpec {
-            channels: channels.try_into()?,
-            sample_rate: first.rate(),
-            bits_per_sample: 16,
-            sample_format: hound::SampleFormat::Int,
-        },
-    )?;
-
-    for audio in audio {
-        let data = audio.data(0);
-        let sample_size = 2;
-        let frame_size = sample_size * channels;
-
-        for frame in data.chunks_exact(frame_size.try_into()?) {
-            for ch in 0..channels {
-                let i = (ch * sample_size) as usize;
-                let sample = i16::from_le_bytes([frame[i], frame[i + 1]]);
-                writer.write_sample(sample)?;
+/// The whole audio stream decoded to interleaved i16 PCM, kept around so a seek can rebuild a
+/// WAV buffer (and a fresh macroquad `Sound`) starting at an arbitrary offset instead of only
+/// ever being able to replay the track from the start.
+struct AudioTrack {
+    channels: u16,
+    sample_rate: u32,
+    samples: Vec<i16>,
+}
+
+impl AudioTrack {
+    fn collect(mut audio: iter::Peekable<impl Iterator<Item = Audio>>) -> eyre::Result<Self> {
+        let first = audio.peek().context("empty audio stream")?;
+        let channels: u16 = first.ch_layout().channels().try_into()?;
+        let sample_rate = first.rate();
+
+        let mut samples = Vec::new();
+        for audio in audio {
+            let data = audio.data(0);
+            let sample_size = 2;
+            let frame_size = sample_size * usize::from(channels);
+
+            for frame in data.chunks_exact(frame_size) {
+                for ch in 0..usize::from(channels) {
+                    let i = ch * sample_size;
+                    samples.push(i16::from_le_bytes([frame[i], frame[i + 1]]));
+                }
             }
         }
+
+        Ok(Self {
+            channels,
+            sample_rate,
+            samples,
+        })
+    }
+
+    fn encode_wav(&self) -> eyre::Result<Vec<u8>> {
+        self.encode_wav_from(Duration::ZERO)
+    }
+
+    /// Re-encodes the track as a standalone WAV starting at `position`, for seeking.
+    fn encode_wav_from(&self, position: Duration) -> eyre::Result<Vec<u8>> {
+        let start_frame = (position.as_secs_f64() * self.sample_rate as f64) as usize;
+        let start_sample = start_frame
+            .saturating_mul(usize::from(self.channels))
+            .min(self.samples.len());
+
+        let mut buffer = Vec::new();
+        let cursor = std::io::Cursor::new(&mut buffer);
+        let mut writer = hound::WavWriter::new(
+            cursor,
+            hound::WavSpec {
+                channels: self.channels,
+                sample_rate: self.sample_rate,
+                bits_per_sample: 16,
+                sample_format: hound::SampleFormat::Int,
+            },
+        )?;
+
+        for &sample in &self.samples[start_sample..] {
+            writer.write_sample(sample)?;
+        }
+        writer.finalize()?;
+        Ok(buffer)
     }
-    writer.finalize()?;
-    Ok(buffer)
 }
 
 #[macroquad::main("MyGame")]
@@ -312,10 +695,30 @@ async fn main() -> eyre::Result<()> {
     ffmpeg::init()?;
     rand::srand(miniquad::date::now().to_bits());
 
-    let mut input = ffmpeg::format::input("prodigy.webm")?;
-    let mut video_player = get_video_player(&mut input).await?;
+    let mut video_player = VideoPlayer::from_path("prodigy.webm").await?;
 
     loop {
+        if is_key_pressed(KeyCode::Space) {
+            video_player.toggle_pause().await?;
+        }
+        if is_key_pressed(KeyCode::Right) {
+            video_player.seek_relative(SEEK_STEP, true).await?;
+        }
+        if is_key_pressed(KeyCode::Left) {
+            video_player.seek_relative(SEEK_STEP, false).await?;
+        }
+        if is_key_pressed(KeyCode::O) {
+            video_player.osd.toggle();
+        }
+        if video_player.is_paused() {
+            if is_key_pressed(KeyCode::Period) {
+                video_player.step_forward();
+            }
+            if is_key_pressed(KeyCode::Comma) {
+                video_player.step_backward().await?;
+            }
+        }
+
         video_player.draw_video_by_frame();
         next_frame().await;
     }
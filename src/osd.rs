@@ -0,0 +1,72 @@
+//! Time/duration/pause/seek overlay, toggled with a hotkey.
+
+use std::time::{Duration, Instant};
+
+use macroquad::prelude::*;
+
+const SEEK_FEEDBACK_LIFETIME: Duration = Duration::from_secs(2);
+
+pub struct Osd {
+    visible: bool,
+    seeked_at: Option<Instant>,
+}
+
+impl Osd {
+    pub fn new() -> Self {
+        Self {
+            visible: true,
+            seeked_at: None,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    pub fn note_seek(&mut self) {
+        self.seeked_at = Some(Instant::now());
+    }
+
+    pub fn draw(&self, position: Duration, duration: Duration, paused: bool) {
+        if !self.visible {
+            return;
+        }
+
+        let y = screen_height() - 40.;
+        let paused_suffix = if paused { "  [paused]" } else { "" };
+        draw_text(
+            &format!(
+                "{} / {}{paused_suffix}",
+                format_timestamp(position),
+                format_timestamp(duration),
+            ),
+            20.,
+            y,
+            30.,
+            WHITE,
+        );
+
+        let flashing_seek = self
+            .seeked_at
+            .is_some_and(|at| at.elapsed() < SEEK_FEEDBACK_LIFETIME);
+        if flashing_seek {
+            draw_text(
+                &format!("seek: {}", format_timestamp(position)),
+                20.,
+                y - 30.,
+                26.,
+                YELLOW,
+            );
+        }
+    }
+}
+
+fn format_timestamp(duration: Duration) -> String {
+    let total_secs = duration.as_secs();
+    format!(
+        "{:02}:{:02}:{:02}",
+        total_secs / 3600,
+        (total_secs / 60) % 60,
+        total_secs % 60
+    )
+}
@@ -0,0 +1,104 @@
+//! Subtitle decoding: text formats (SRT/WebVTT/ASS) become plain strings, bitmap formats
+//! (DVB/PGS) get expanded from their palette to an RGBA overlay texture.
+
+use std::{ffi::CStr, os::raw::c_char, time::Duration};
+
+use ffmpeg_the_third::{codec::subtitle, ffi};
+use macroquad::texture::Texture2D;
+
+pub struct SubtitleCue {
+    pub start: Duration,
+    pub end: Duration,
+    pub content: SubtitleContent,
+}
+
+pub enum SubtitleContent {
+    Text(String),
+    /// `x`/`y` are in source-video pixel space, not remapped through the letterbox/scale graph
+    /// in [`crate::retain_aspect_ratio_scale`] - only placed correctly at the source resolution.
+    Bitmap { texture: Texture2D, x: i32, y: i32 },
+}
+
+/// A subtitle can carry several rects at once (e.g. two speakers' lines simultaneously).
+pub fn cues_from(packet_pts: Duration, subtitle: &subtitle::Subtitle) -> Vec<SubtitleCue> {
+    let raw = unsafe { &*subtitle.as_ptr() };
+    let start = packet_pts + Duration::from_millis(raw.start_display_time.into());
+    let end = packet_pts + Duration::from_millis(raw.end_display_time.into());
+
+    (0..raw.num_rects as isize)
+        .filter_map(|i| {
+            let rect = unsafe { &**raw.rects.offset(i) };
+            let content = match rect.type_ {
+                ffi::AVSubtitleType::SUBTITLE_TEXT => {
+                    SubtitleContent::Text(cstr_to_string(rect.text))
+                }
+                ffi::AVSubtitleType::SUBTITLE_ASS => {
+                    SubtitleContent::Text(strip_ass_markup(&cstr_to_string(rect.ass)))
+                }
+                ffi::AVSubtitleType::SUBTITLE_BITMAP => bitmap_to_content(rect)?,
+                _ => return None,
+            };
+            Some(SubtitleCue { start, end, content })
+        })
+        .collect()
+}
+
+fn cstr_to_string(ptr: *mut c_char) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+    unsafe { CStr::from_ptr(ptr) }.to_string_lossy().into_owned()
+}
+
+fn strip_ass_markup(line: &str) -> String {
+    // ReadOrder,Layer,Style,Name,MarginL,MarginR,MarginV,Effect,Text - 8 commas before Text.
+    let text = line.splitn(9, ',').last().unwrap_or(line);
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                while chars.next_if(|&c| c != '}').is_some() {}
+                chars.next();
+            }
+            '\\' if matches!(chars.peek(), Some('N') | Some('n')) => {
+                chars.next();
+                out.push('\n');
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+// `data[0]` is one palette index per pixel, `data[1]` is the palette as packed native-endian ARGB.
+fn bitmap_to_content(rect: &ffi::AVSubtitleRect) -> Option<SubtitleContent> {
+    let width = rect.w as usize;
+    let height = rect.h as usize;
+    if width == 0 || height == 0 {
+        return None;
+    }
+
+    let indices = rect.data[0];
+    let palette = rect.data[1].cast::<u32>();
+    if indices.is_null() || palette.is_null() {
+        return None;
+    }
+    let stride = rect.linesize[0] as usize;
+
+    let mut rgba = vec![0u8; width * height * 4];
+    for y in 0..height {
+        for x in 0..width {
+            let index = unsafe { *indices.add(y * stride + x) } as usize;
+            let [b, g, r, a] = unsafe { *palette.add(index) }.to_ne_bytes();
+            let offset = (y * width + x) * 4;
+            rgba[offset..offset + 4].copy_from_slice(&[r, g, b, a]);
+        }
+    }
+
+    Some(SubtitleContent::Bitmap {
+        texture: Texture2D::from_rgba8(width.try_into().ok()?, height.try_into().ok()?, &rgba),
+        x: rect.x,
+        y: rect.y,
+    })
+}